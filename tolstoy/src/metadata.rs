@@ -35,6 +35,21 @@ pub enum PartitionsTable {
     Tolstoy,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncState {
+    Same,
+    LocalAhead,
+    RemoteAhead,
+    Diverged {
+        common: Entid,
+        local_txs: Vec<Entid>,
+        remote_txs: Vec<Entid>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionDiff(pub Vec<(String, Entid)>);
+
 impl SyncMetadataClient {
     pub fn remote_head(tx: &rusqlite::Transaction) -> Result<Uuid> {
         tx.query_row(
@@ -56,6 +71,63 @@ impl SyncMetadataClient {
         Ok(())
     }
 
+    // Defaults to 0, which sorts before any real tx entid and so is treated
+    // as "nothing synced yet".
+    pub fn synced_tx(tx: &rusqlite::Transaction) -> Result<Entid> {
+        tx.query_row(
+            "SELECT value FROM tolstoy_metadata WHERE key = ?",
+            &[&schema::SYNCED_TX_KEY], |r| r.get(0)
+        ).map_err(|e| e.into())
+    }
+
+    pub fn set_synced_tx(tx: &rusqlite::Transaction, entid: Entid) -> Result<()> {
+        let updated = tx.execute("UPDATE tolstoy_metadata SET value = ? WHERE key = ?",
+            &[&entid, &schema::SYNCED_TX_KEY])?;
+        if updated != 1 {
+            bail!(TolstoyError::DuplicateMetadata(schema::SYNCED_TX_KEY.into()));
+        }
+        Ok(())
+    }
+
+    // `remote_txs` is expected to already be partitioned by the caller to exclude
+    // anything at or before the locally-recorded synced_tx (c.f. root_and_head_tx,
+    // which callers use to request a tx range from the remote).
+    pub fn sync_state(tx: &rusqlite::Transaction, remote_head: &Uuid, remote_txs: &[Entid]) -> Result<SyncState> {
+        let stored_remote_head = Self::remote_head(tx)?;
+        let synced_tx = Self::synced_tx(tx)?;
+
+        let local_txs = Self::ordered_txs(tx)?;
+        if synced_tx != 0 && !local_txs.contains(&synced_tx) {
+            bail!(TolstoyError::UnexpectedState(
+                format!("Synced tx {} is no longer present in the local transaction log", synced_tx)));
+        }
+
+        // synced_tx of 0 means nothing has synced yet; the real common
+        // ancestor is the root tx rather than the sentinel itself, so the
+        // root tx is shared and never counted as local-only.
+        let common = if synced_tx == 0 {
+            local_txs.first().cloned().unwrap_or(0)
+        } else {
+            synced_tx
+        };
+
+        let local_only: Vec<Entid> = local_txs.into_iter().filter(|t| *t > common).collect();
+        let remote_changed = stored_remote_head != *remote_head;
+
+        match (remote_changed, local_only.is_empty()) {
+            (false, true) => Ok(SyncState::Same),
+            (false, false) => Ok(SyncState::LocalAhead),
+            (true, true) => Ok(SyncState::RemoteAhead),
+            (true, false) => {
+                Ok(SyncState::Diverged {
+                    common,
+                    local_txs: local_only,
+                    remote_txs: remote_txs.to_vec(),
+                })
+            },
+        }
+    }
+
     // TODO Functions below start to blur the line between mentat-proper and tolstoy...
     pub fn get_partitions(tx: &rusqlite::Transaction, parts_table: PartitionsTable) -> Result<PartitionMap> {
         let db_table = match parts_table {
@@ -69,22 +141,71 @@ impl SyncMetadataClient {
         m
     }
 
-    pub fn root_and_head_tx(tx: &rusqlite::Transaction) -> Result<(Entid, Entid)> {
+    // Bails if a partition is present in one map but missing from the other, since
+    // that means the two dbs disagree about which partitions even exist.
+    pub fn reconcile_partitions(core: &PartitionMap, tolstoy: &PartitionMap) -> Result<PartitionDiff> {
+        let mut advances = vec![];
+
+        for (part, core_partition) in core.iter() {
+            let tolstoy_partition = match tolstoy.get(part) {
+                Some(p) => p,
+                None => bail!(TolstoyError::UnexpectedState(
+                    format!("Partition '{}' is present in core parts but missing from tolstoy_parts", part))),
+            };
+
+            if tolstoy_partition.index > core_partition.index {
+                advances.push((part.clone(), tolstoy_partition.index));
+            }
+        }
+
+        for part in tolstoy.keys() {
+            if !core.contains_key(part) {
+                bail!(TolstoyError::UnexpectedState(
+                    format!("Partition '{}' is present in tolstoy_parts but missing from core parts", part)));
+            }
+        }
+
+        Ok(PartitionDiff(advances))
+    }
+
+    pub fn set_partitions(tx: &rusqlite::Transaction, parts: &PartitionMap, parts_table: PartitionsTable) -> Result<()> {
+        let db_table = match parts_table {
+            PartitionsTable::Core => "parts",
+            PartitionsTable::Tolstoy => "tolstoy_parts"
+        };
+
+        for (part, partition) in parts.iter() {
+            let updated = tx.execute(
+                &format!("UPDATE {} SET start = ?, end = ?, idx = ? WHERE part = ?", db_table),
+                &[&partition.start, &partition.end, &partition.index, part])?;
+            if updated != 1 {
+                bail!(TolstoyError::UnexpectedState(format!("Could not advance partition '{}'", part)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ordered_txs(tx: &rusqlite::Transaction) -> Result<Vec<Entid>> {
         let mut stmt: ::rusqlite::Statement = tx.prepare("SELECT tx FROM transactions GROUP BY tx ORDER BY tx")?;
-        let txs: Vec<_> = stmt.query_and_then(&[], |row| -> Result<Entid> {
+        let txs: Result<Vec<Entid>> = stmt.query_and_then(&[], |row| -> Result<Entid> {
             Ok(row.get_checked(0)?)
         })?.collect();
+        txs
+    }
 
+    pub fn root_and_head_tx(tx: &rusqlite::Transaction) -> Result<(Entid, Entid)> {
+        let txs = Self::ordered_txs(tx)?;
         let mut txs = txs.into_iter();
 
         let root_tx = match txs.nth(0) {
             None => bail!(TolstoyError::UnexpectedState(format!("Could not get root tx"))),
-            Some(t) => t?
+            Some(t) => t
         };
 
         match txs.last() {
             None => Ok((root_tx, root_tx)),
-            Some(t) => Ok((root_tx, t?))
+            Some(t) => Ok((root_tx, t))
         }
     }
 }
@@ -143,4 +264,101 @@ mod tests {
         assert_eq!(268435456, root_tx);
         assert_eq!(268435457, last_tx);
     }
+
+    #[test]
+    fn test_sync_state_same() {
+        let mut conn = schema::tests::setup_conn_bare();
+        db::ensure_current_version(&mut conn).expect("mentat db init");
+        let db_tx = conn.transaction().expect("transaction");
+        schema::ensure_current_version(&db_tx).expect("tolstoy schema init");
+
+        let remote_head = SyncMetadataClient::remote_head(&db_tx).expect("fetch succeeded");
+        let state = SyncMetadataClient::sync_state(&db_tx, &remote_head, &[]).expect("sync_state succeeded");
+        assert_eq!(SyncState::Same, state);
+    }
+
+    #[test]
+    fn test_sync_state_local_ahead() {
+        let mut conn = schema::tests::setup_conn_bare();
+        db::ensure_current_version(&mut conn).expect("mentat db init");
+        let db_tx = conn.transaction().expect("transaction");
+        schema::ensure_current_version(&db_tx).expect("tolstoy schema init");
+
+        db_tx.execute("INSERT INTO transactions VALUES (?, ?, ?, ?, ?, ?)", &[&268435457, &3, &1529971773701734_i64, &268435457, &1, &4]).expect("inserted");
+
+        let remote_head = SyncMetadataClient::remote_head(&db_tx).expect("fetch succeeded");
+        let state = SyncMetadataClient::sync_state(&db_tx, &remote_head, &[]).expect("sync_state succeeded");
+        assert_eq!(SyncState::LocalAhead, state);
+    }
+
+    #[test]
+    fn test_sync_state_diverged() {
+        let mut conn = schema::tests::setup_conn_bare();
+        db::ensure_current_version(&mut conn).expect("mentat db init");
+        let db_tx = conn.transaction().expect("transaction");
+        schema::ensure_current_version(&db_tx).expect("tolstoy schema init");
+
+        db_tx.execute("INSERT INTO transactions VALUES (?, ?, ?, ?, ?, ?)", &[&268435457, &3, &1529971773701734_i64, &268435457, &1, &4]).expect("inserted");
+
+        let remote_head = Uuid::new_v4();
+        let remote_txs = vec![268435458];
+        let state = SyncMetadataClient::sync_state(&db_tx, &remote_head, &remote_txs).expect("sync_state succeeded");
+        assert_eq!(SyncState::Diverged {
+            common: 268435456,
+            local_txs: vec![268435457],
+            remote_txs: remote_txs,
+        }, state);
+    }
+
+    #[test]
+    fn test_sync_state_missing_synced_tx() {
+        let mut conn = schema::tests::setup_conn_bare();
+        db::ensure_current_version(&mut conn).expect("mentat db init");
+        let db_tx = conn.transaction().expect("transaction");
+        schema::ensure_current_version(&db_tx).expect("tolstoy schema init");
+
+        SyncMetadataClient::set_synced_tx(&db_tx, 42).expect("update succeeded");
+
+        let remote_head = SyncMetadataClient::remote_head(&db_tx).expect("fetch succeeded");
+        SyncMetadataClient::sync_state(&db_tx, &remote_head, &[]).expect_err("synced tx is missing from the log");
+    }
+
+    #[test]
+    fn test_reconcile_and_set_partitions() {
+        let mut conn = schema::tests::setup_conn_bare();
+        db::ensure_current_version(&mut conn).expect("mentat db init");
+        let db_tx = conn.transaction().expect("transaction");
+        schema::ensure_current_version(&db_tx).expect("tolstoy schema init");
+
+        let core = SyncMetadataClient::get_partitions(&db_tx, PartitionsTable::Core).expect("core partitions");
+
+        // Populate the real tolstoy_parts table, as a remote sync would, with
+        // each partition's idx bumped past what core currently has.
+        for (part, partition) in core.iter() {
+            db_tx.execute("INSERT INTO tolstoy_parts (part, start, end, idx) VALUES (?, ?, ?, ?)",
+                &[part, &partition.start, &partition.end, &(partition.index + 1)]).expect("inserted");
+        }
+        let tolstoy = SyncMetadataClient::get_partitions(&db_tx, PartitionsTable::Tolstoy).expect("tolstoy partitions");
+
+        let diff = SyncMetadataClient::reconcile_partitions(&core, &tolstoy).expect("reconciled");
+        assert_eq!(core.len(), diff.0.len());
+
+        SyncMetadataClient::set_partitions(&db_tx, &tolstoy, PartitionsTable::Core).expect("set succeeded");
+        let updated = SyncMetadataClient::get_partitions(&db_tx, PartitionsTable::Core).expect("core partitions");
+        assert_eq!(tolstoy, updated);
+    }
+
+    #[test]
+    fn test_reconcile_partitions_missing() {
+        let mut conn = schema::tests::setup_conn_bare();
+        db::ensure_current_version(&mut conn).expect("mentat db init");
+        let db_tx = conn.transaction().expect("transaction");
+
+        let core = SyncMetadataClient::get_partitions(&db_tx, PartitionsTable::Core).expect("core partitions");
+        let mut tolstoy = core.clone();
+        let missing_key = tolstoy.keys().next().cloned().expect("at least one partition");
+        tolstoy.remove(&missing_key);
+
+        SyncMetadataClient::reconcile_partitions(&core, &tolstoy).expect_err("missing partition should bail");
+    }
 }
@@ -0,0 +1,57 @@
+// Copyright 2018 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use rusqlite;
+use uuid::Uuid;
+
+use errors::Result;
+
+pub static REMOTE_HEAD_KEY: &'static str = "remote_head";
+pub static SYNCED_TX_KEY: &'static str = "synced_tx";
+
+pub fn ensure_current_version(tx: &rusqlite::Transaction) -> Result<()> {
+    tx.execute_batch("
+        CREATE TABLE IF NOT EXISTS tolstoy_metadata (
+            key TEXT NOT NULL PRIMARY KEY,
+            value BLOB NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS tolstoy_parts (
+            part TEXT NOT NULL PRIMARY KEY,
+            start INTEGER NOT NULL,
+            end INTEGER NOT NULL,
+            idx INTEGER NOT NULL
+        );
+        ")?;
+
+    let nil_uuid_bytes = Uuid::nil().as_bytes().to_vec();
+    tx.execute("INSERT OR IGNORE INTO tolstoy_metadata (key, value) VALUES (?, ?)",
+        &[&REMOTE_HEAD_KEY, &nil_uuid_bytes])?;
+    tx.execute("INSERT OR IGNORE INTO tolstoy_metadata (key, value) VALUES (?, ?)",
+        &[&SYNCED_TX_KEY, &0i64])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    pub fn setup_conn_bare() -> Connection {
+        Connection::open_in_memory().expect("in-memory db")
+    }
+
+    pub fn setup_tx(conn: &mut Connection) -> rusqlite::Transaction {
+        let tx = conn.transaction().expect("transaction");
+        ensure_current_version(&tx).expect("tolstoy schema init");
+        tx
+    }
+}